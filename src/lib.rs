@@ -20,10 +20,18 @@
 //! # Ok::<(), io::Error>(())
 //!```
 //!
+//! Or let `readpass` print the prompt to the TTY directly, so it's shown
+//! even if stdout and stderr are redirected:
+//!
+//!```rust,no_run
+//! let passwd = readpass::from_tty_with_prompt("Please enter a password: ")?;
+//! # Ok::<(), std::io::Error>(())
+//!```
+//!
 //! [`String`]s returned by `readpass` are wrapped in [`Zeroizing`]
 //! to ensure the password is zeroized from memory after it's [`Drop`]ped.
 
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Read};
 
 use zeroize::Zeroizing;
 
@@ -37,10 +45,47 @@ mod windows;
 #[cfg(windows)]
 use windows as sys;
 
-pub use sys::from_tty;
+pub use sys::{from_tty, from_tty_masked, from_tty_with_max_len, from_tty_with_prompt, is_tty};
 
 const CTRL_U: char = char::from_u32(21).unwrap();
 
+/// The default maximum number of bytes [`from_tty`] will read before giving up.
+///
+/// This bounds how much an attacker who manages to substitute the TTY mid-read
+/// (see [`from_tty_with_max_len`]) can inject before the read is aborted.
+pub const DEFAULT_MAX_LEN: usize = 10 * 1024;
+
+/// Reads a password from the TTY if stdin is a terminal, or falls back to
+/// reading a single line from stdin otherwise.
+///
+/// `from_tty` fails outright if `/dev/tty` can't be opened, e.g. when fully
+/// detached from a terminal such as in a container. This helper makes that
+/// case work instead of panicking or erroring, at the cost of the input no
+/// longer being hidden when it's piped or redirected.
+///
+/// # Errors
+///
+/// This function will return an I/O error if reading fails.
+pub fn from_tty_or_stdin() -> io::Result<Zeroizing<String>> {
+    if is_tty() {
+        from_tty()
+    } else {
+        from_bufread(&mut io::stdin().lock())
+    }
+}
+
+/// Reads a password from an `impl BufRead`.
+///
+/// This only reads the first line from the reader, so it can be called
+/// repeatedly to read multiple passwords from the same stream.
+/// Newlines and carriage returns are trimmed from the end of the resulting [`String`].
+///
+/// This is useful for reading passwords from sources other than a TTY,
+/// such as a pipe, a file, or redirected stdin.
+pub fn from_reader(reader: &mut impl BufRead) -> io::Result<Zeroizing<String>> {
+    from_bufread(reader)
+}
+
 /// Reads a password from an `impl BufRead`.
 ///
 /// This only reads the first line from the reader.
@@ -48,17 +93,102 @@ const CTRL_U: char = char::from_u32(21).unwrap();
 fn from_bufread(reader: &mut impl BufRead) -> io::Result<Zeroizing<String>> {
     let mut password = Zeroizing::new(String::new());
     reader.read_line(&mut password)?;
+    Ok(process_line(password))
+}
+
+/// Reads a password from an `impl BufRead`, reading at most `max_len` bytes.
+///
+/// Unlike [`from_bufread`], this returns an error instead of silently
+/// truncating the password if the line isn't newline-terminated within
+/// `max_len` bytes, e.g. because more input than that was injected.
+pub(crate) fn from_bufread_limited(
+    reader: &mut impl BufRead,
+    max_len: usize,
+) -> io::Result<Zeroizing<String>> {
+    let mut raw = Zeroizing::new(String::new());
+    let mut limited = reader.take(max_len as u64);
+    limited.read_line(&mut raw)?;
+
+    // `limit() == 0` alone doesn't mean we overflowed: a line whose content
+    // and terminator together exactly fill `max_len` bytes also drains the
+    // limit to zero. Only treat it as an overflow if no terminator was found.
+    if !raw.ends_with('\n') && limited.limit() == 0 {
+        return Err(io::Error::other(
+            "password exceeds the maximum allowed length",
+        ));
+    }
 
+    Ok(process_line(raw))
+}
+
+/// Trims the trailing newline/carriage return from a line read by
+/// [`from_bufread`] or [`from_bufread_limited`], and applies the Ctrl-U
+/// line-clear convention.
+fn process_line(mut password: Zeroizing<String>) -> Zeroizing<String> {
     let len = password.trim_end_matches(&['\r', '\n'][..]).len();
     password.truncate(len);
 
     // Ctrl-U should remove the line in terminals.
-    password = match password.rfind(CTRL_U) {
+    match password.rfind(CTRL_U) {
         Some(last_ctrl_u_index) => Zeroizing::new(password[last_ctrl_u_index + 1..].to_string()),
         None => password,
+    }
+}
+
+/// The outcome of decoding a single logical keystroke via [`read_utf8_char`].
+#[derive(Debug, PartialEq)]
+pub(crate) enum Utf8Char {
+    /// A full, valid character.
+    Char(char),
+    /// The bytes read didn't form valid UTF-8; nothing should be pushed.
+    Invalid,
+    /// A line terminator (`\n` or `\r`) showed up where a continuation byte
+    /// was expected, e.g. because `first_byte` was a stray/malicious lead
+    /// byte with no continuation bytes behind it. Callers should treat this
+    /// the same as reading the terminator directly instead of swallowing it
+    /// as part of a failed decode.
+    Terminator,
+}
+
+/// Decodes a single UTF-8 character from `reader`, given its already-read
+/// first byte, reading whatever continuation bytes the leading byte implies.
+///
+/// Used by the masked-echo readers, which read one character at a time
+/// instead of a whole line. Continuation bytes are read one at a time so a
+/// `\n`/`\r` arriving where a continuation byte was expected can be reported
+/// as [`Utf8Char::Terminator`] instead of being consumed as part of a failed
+/// decode.
+pub(crate) fn read_utf8_char(reader: &mut impl Read, first_byte: u8) -> io::Result<Utf8Char> {
+    let seq_len = match first_byte {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        // Not a valid UTF-8 leading byte; let `from_utf8` below reject it.
+        _ => 1,
     };
 
-    Ok(password)
+    let mut buf = [0u8; 4];
+    buf[0] = first_byte;
+    for slot in buf.iter_mut().take(seq_len).skip(1) {
+        let mut byte = [0u8; 1];
+        if reader.read(&mut byte)? == 0 {
+            // EOF mid-sequence.
+            return Ok(Utf8Char::Invalid);
+        }
+        if byte[0] == b'\n' || byte[0] == b'\r' {
+            return Ok(Utf8Char::Terminator);
+        }
+        *slot = byte[0];
+    }
+
+    Ok(match std::str::from_utf8(&buf[..seq_len])
+        .ok()
+        .and_then(|s| s.chars().next())
+    {
+        Some(c) => Utf8Char::Char(c),
+        None => Utf8Char::Invalid,
+    })
 }
 
 #[cfg(test)]
@@ -89,6 +219,62 @@ mod tests {
         assert_eq!(*response, "Another mocked response.");
     }
 
+    #[test]
+    fn can_read_from_reader_many_times() {
+        let mut reader_crlf = mock_input_crlf();
+
+        let response = super::from_reader(&mut reader_crlf).unwrap();
+        assert_eq!(*response, "A mocked response.");
+        let response = super::from_reader(&mut reader_crlf).unwrap();
+        assert_eq!(*response, "Another mocked response.");
+
+        let mut reader_lf = mock_input_lf();
+        let response = super::from_reader(&mut reader_lf).unwrap();
+        assert_eq!(*response, "A mocked response.");
+        let response = super::from_reader(&mut reader_lf).unwrap();
+        assert_eq!(*response, "Another mocked response.");
+    }
+
+    #[test]
+    fn limited_read_accepts_line_that_exactly_fills_max_len() {
+        // 9-char password + '\n' terminator = exactly 10 bytes.
+        let mut reader = Cursor::new(&b"123456789\n"[..]);
+        let response = super::from_bufread_limited(&mut reader, 10).unwrap();
+        assert_eq!(*response, "123456789");
+    }
+
+    #[test]
+    fn limited_read_rejects_unterminated_overflow() {
+        let mut reader = Cursor::new(&b"0123456789 and then some more\n"[..]);
+        let err = super::from_bufread_limited(&mut reader, 10).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn read_utf8_char_decodes_multibyte_sequences() {
+        let bytes = "é".as_bytes();
+        let mut rest = Cursor::new(&bytes[1..]);
+        let c = super::read_utf8_char(&mut rest, bytes[0]).unwrap();
+        assert_eq!(c, super::Utf8Char::Char('é'));
+    }
+
+    #[test]
+    fn read_utf8_char_reports_terminator_instead_of_swallowing_it() {
+        // A lead byte claiming a 2-byte sequence, immediately followed by a
+        // newline: the newline must end the read, not get eaten as a
+        // (invalid) continuation byte.
+        let mut rest = Cursor::new(&b"\n"[..]);
+        let result = super::read_utf8_char(&mut rest, 0xc0).unwrap();
+        assert_eq!(result, super::Utf8Char::Terminator);
+    }
+
+    #[test]
+    fn read_utf8_char_reports_invalid_on_truncated_eof() {
+        let mut rest = Cursor::new(&b""[..]);
+        let result = super::read_utf8_char(&mut rest, 0xc0).unwrap();
+        assert_eq!(result, super::Utf8Char::Invalid);
+    }
+
     // These tests check whether or not we can read from a reader when
     // stdin is not a terminal.
 
@@ -1,11 +1,35 @@
 use std::fs::File;
-use std::io::{self, BufReader};
+use std::io::{self, BufReader, Read, Write};
 use std::mem::MaybeUninit;
 use std::os::unix::io::AsRawFd;
 
-use libc::{c_int, tcgetattr, tcsetattr, termios, ECHO, ECHONL, TCSANOW};
+use libc::{c_int, tcgetattr, tcsetattr, termios, ECHO, ECHONL, ICANON, TCSANOW, VMIN, VTIME};
 use zeroize::Zeroizing;
 
+/// A fingerprint of the TTY's identity, used to detect if the fd was closed
+/// and reopened to point at something else mid-read.
+#[derive(PartialEq)]
+struct TtyFingerprint {
+    dev: libc::dev_t,
+    ino: libc::ino_t,
+}
+
+/// Fingerprints the fd, failing if it isn't a TTY.
+fn tty_fingerprint(fd: i32) -> io::Result<TtyFingerprint> {
+    if unsafe { libc::isatty(fd) } == 0 {
+        return Err(io::Error::other("fd is not a tty"));
+    }
+
+    let mut stat_uninit = MaybeUninit::<libc::stat>::uninit();
+    io_result(unsafe { libc::fstat(fd, stat_uninit.as_mut_ptr()) })?;
+    let stat = unsafe { stat_uninit.assume_init() };
+
+    Ok(TtyFingerprint {
+        dev: stat.st_dev,
+        ino: stat.st_ino,
+    })
+}
+
 struct HiddenInput {
     fd: i32,
     term_orig: termios,
@@ -43,6 +67,41 @@ impl Drop for HiddenInput {
     }
 }
 
+struct RawInput {
+    fd: i32,
+    term_orig: termios,
+}
+
+impl RawInput {
+    fn new(fd: i32) -> io::Result<RawInput> {
+        let mut term_uninit = MaybeUninit::<termios>::uninit();
+        io_result(unsafe { tcgetattr(fd, term_uninit.as_mut_ptr()) })?;
+        let mut term = unsafe { term_uninit.assume_init() };
+        let term_orig = term;
+
+        // Disable canonical mode so we can read one character at a time, and
+        // disable echo so we can print the mask character ourselves instead.
+        term.c_lflag &= !(ICANON | ECHO);
+
+        // Read as soon as a single byte is available.
+        term.c_cc[VMIN] = 1;
+        term.c_cc[VTIME] = 0;
+
+        io_result(unsafe { tcsetattr(fd, TCSANOW, &term) })?;
+
+        Ok(RawInput { fd, term_orig })
+    }
+}
+
+impl Drop for RawInput {
+    fn drop(&mut self) {
+        // Set the the mode back to normal.
+        unsafe {
+            tcsetattr(self.fd, TCSANOW, &self.term_orig);
+        }
+    }
+}
+
 /// Turns a C function return into an IO Result.
 fn io_result(ret: c_int) -> io::Result<()> {
     match ret {
@@ -51,18 +110,147 @@ fn io_result(ret: c_int) -> io::Result<()> {
     }
 }
 
+/// Returns whether stdin is attached to a terminal.
+pub fn is_tty() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) != 0 }
+}
+
 /// Reads a password from the TTY.
 ///
 /// Newlines and carriage returns are trimmed from the end of the resulting `String`.
+/// At most [`crate::DEFAULT_MAX_LEN`] bytes are read; see [`from_tty_with_max_len`]
+/// to configure this.
 ///
 /// # Errors
 ///
 /// This function will return an I/O error if reading from `/dev/tty` fails.
 pub fn from_tty() -> io::Result<Zeroizing<String>> {
+    from_tty_with_max_len(crate::DEFAULT_MAX_LEN)
+}
+
+/// Reads a password from the TTY, reading at most `max_len` bytes.
+///
+/// To shrink the window for an attacker (e.g. FFI code) closing and
+/// reopening `/dev/tty` mid-read to inject a canned response, the fd's
+/// identity (its device/inode, and that it's still a TTY) is fingerprinted
+/// before the read and re-checked after it; a mismatch is reported as an
+/// I/O error. `max_len` bounds how much such an injected blob could be,
+/// shrinking the race window further.
+///
+/// Newlines and carriage returns are trimmed from the end of the resulting `String`.
+///
+/// # Errors
+///
+/// This function will return an I/O error if reading from `/dev/tty` fails,
+/// if no newline is found within the first `max_len` bytes, or if the TTY's
+/// identity changes between the start and the end of the read.
+pub fn from_tty_with_max_len(max_len: usize) -> io::Result<Zeroizing<String>> {
     let tty = File::open("/dev/tty")?;
     let fd = tty.as_raw_fd();
+    let fingerprint = tty_fingerprint(fd)?;
+    let mut reader = BufReader::new(tty);
+
+    let _hidden_input = HiddenInput::new(fd)?;
+
+    let password = crate::from_bufread_limited(&mut reader, max_len)?;
+
+    if tty_fingerprint(fd)? != fingerprint {
+        return Err(io::Error::other(
+            "tty identity changed while reading the password",
+        ));
+    }
+
+    Ok(password)
+}
+
+/// Reads a password from the TTY, printing `mask` in place of every character typed.
+///
+/// Unlike [`from_tty`], this puts the terminal into raw mode and reads one byte
+/// at a time, so it can echo the mask character itself. Backspace removes the
+/// last character, and Ctrl-U clears the whole line.
+///
+/// # Errors
+///
+/// This function will return an I/O error if reading from or writing to `/dev/tty` fails.
+pub fn from_tty_masked(mask: char) -> io::Result<Zeroizing<String>> {
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    let fd = tty.as_raw_fd();
+
+    let _raw_input = RawInput::new(fd)?;
+
+    let mut password = Zeroizing::new(String::new());
+    let mut byte = [0u8; 1];
+    loop {
+        if tty.read(&mut byte)? == 0 {
+            // EOF.
+            break;
+        }
+
+        match byte[0] {
+            b'\n' | b'\r' => {
+                tty.write_all(b"\n")?;
+                break;
+            }
+            // Backspace/Delete.
+            0x7f | 0x08 => {
+                if password.pop().is_some() {
+                    tty.write_all(b"\x08 \x08")?;
+                }
+            }
+            // Ctrl-U: clear the line.
+            0x15 => {
+                let erase = "\x08 \x08".repeat(password.chars().count());
+                password.clear();
+                tty.write_all(erase.as_bytes())?;
+            }
+            first_byte => match crate::read_utf8_char(&mut tty, first_byte)? {
+                crate::Utf8Char::Char(c) => {
+                    password.push(c);
+                    write!(tty, "{mask}")?;
+                }
+                crate::Utf8Char::Invalid => {}
+                crate::Utf8Char::Terminator => {
+                    tty.write_all(b"\n")?;
+                    break;
+                }
+            },
+        }
+    }
+
+    Ok(password)
+}
+
+/// Writes `prompt` to the TTY, then reads a password from it the same way [`from_tty`] does.
+///
+/// The prompt is written directly to `/dev/tty` rather than stdout or stderr,
+/// so it's displayed even when those streams are redirected.
+///
+/// # Errors
+///
+/// This function will return an I/O error if writing to or reading from `/dev/tty` fails.
+pub fn from_tty_with_prompt(prompt: &str) -> io::Result<Zeroizing<String>> {
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    write!(tty, "{prompt}")?;
+    tty.flush()?;
+
+    let fd = tty.as_raw_fd();
+    let fingerprint = tty_fingerprint(fd)?;
     let mut reader = BufReader::new(tty);
 
     let _hidden_input = HiddenInput::new(fd)?;
-    crate::from_bufread(&mut reader)
+    let password = crate::from_bufread_limited(&mut reader, crate::DEFAULT_MAX_LEN)?;
+
+    if tty_fingerprint(fd)? != fingerprint {
+        return Err(io::Error::other(
+            "tty identity changed while reading the password",
+        ));
+    }
+
+    Ok(password)
 }
@@ -1,17 +1,82 @@
 use std::fs::File;
-use std::io::{self, BufReader, Write};
+use std::io::{self, BufReader, Read, Write};
 use std::os::windows::io::FromRawHandle;
 
 use windows_sys::core::PCSTR;
-use windows_sys::Win32::Foundation::{GENERIC_READ, GENERIC_WRITE, HANDLE, INVALID_HANDLE_VALUE};
+use windows_sys::Win32::Foundation::{
+    CloseHandle, CompareObjectHandles, DuplicateHandle, DUPLICATE_SAME_ACCESS, GENERIC_READ,
+    GENERIC_WRITE, HANDLE, INVALID_HANDLE_VALUE,
+};
 use windows_sys::Win32::Storage::FileSystem::{
     CreateFileA, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
 };
 use windows_sys::Win32::System::Console::{
-    GetConsoleMode, SetConsoleMode, CONSOLE_MODE, ENABLE_LINE_INPUT, ENABLE_PROCESSED_INPUT,
+    GetConsoleMode, GetStdHandle, SetConsoleMode, CONSOLE_MODE, ENABLE_LINE_INPUT,
+    ENABLE_PROCESSED_INPUT, STD_INPUT_HANDLE,
 };
+use windows_sys::Win32::System::Threading::GetCurrentProcess;
 use zeroize::Zeroizing;
 
+/// A fingerprint of the console handle's identity, used to detect if the
+/// handle was closed and reopened (e.g. by recycled-handle-value tricks) to
+/// point at a different underlying console input buffer mid-read.
+///
+/// Console handles aren't filesystem objects, so there's no device/inode
+/// equivalent to check (`GetFileInformationByHandle` fails on them with
+/// `ERROR_INVALID_FUNCTION`), and a process-wide value like `GetConsoleWindow`
+/// wouldn't change even if the handle itself were substituted. Instead we
+/// keep our own duplicate of the handle: duplicating doesn't track the
+/// source handle's slot, so `duplicate` keeps referring to the original
+/// kernel object no matter what happens to `handle` afterwards.
+/// `CompareObjectHandles` then tells us whether a handle passed in later is
+/// still that same object.
+struct HandleFingerprint {
+    duplicate: HANDLE,
+}
+
+impl HandleFingerprint {
+    /// Captures the handle's identity, failing if it isn't a valid console handle.
+    fn capture(handle: HANDLE) -> io::Result<HandleFingerprint> {
+        let mut mode = 0;
+        if unsafe { GetConsoleMode(handle, &mut mode as *mut CONSOLE_MODE) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let process = unsafe { GetCurrentProcess() };
+        let mut duplicate: HANDLE = 0;
+        if unsafe {
+            DuplicateHandle(
+                process,
+                handle,
+                process,
+                &mut duplicate,
+                0,
+                0,
+                DUPLICATE_SAME_ACCESS,
+            )
+        } == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(HandleFingerprint { duplicate })
+    }
+
+    /// Returns whether `handle` still refers to the object this fingerprint
+    /// was captured from.
+    fn matches(&self, handle: HANDLE) -> bool {
+        unsafe { CompareObjectHandles(self.duplicate, handle) != 0 }
+    }
+}
+
+impl Drop for HandleFingerprint {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.duplicate);
+        }
+    }
+}
+
 struct HiddenInput {
     mode: u32,
     handle: HANDLE,
@@ -45,14 +110,79 @@ impl Drop for HiddenInput {
     }
 }
 
+struct RawInput {
+    mode: u32,
+    handle: HANDLE,
+}
+
+impl RawInput {
+    fn new(handle: HANDLE) -> io::Result<RawInput> {
+        let mut mode = 0;
+
+        // Get the old mode, so that we can reset back to it when we are done.
+        if unsafe { GetConsoleMode(handle, &mut mode as *mut CONSOLE_MODE) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Disable line input so we can read one character at a time, and leave
+        // echo off so we can print the mask character ourselves instead.
+        let new_mode_flags = ENABLE_PROCESSED_INPUT;
+        if unsafe { SetConsoleMode(handle, new_mode_flags) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(RawInput { mode, handle })
+    }
+}
+
+impl Drop for RawInput {
+    fn drop(&mut self) {
+        // Set the mode back to normal.
+        unsafe {
+            SetConsoleMode(self.handle, self.mode);
+        }
+    }
+}
+
+/// Returns whether stdin is attached to a console.
+pub fn is_tty() -> bool {
+    let mut mode = 0;
+    unsafe {
+        let handle = GetStdHandle(STD_INPUT_HANDLE);
+        GetConsoleMode(handle, &mut mode as *mut CONSOLE_MODE) != 0
+    }
+}
+
 /// Reads a password from the TTY.
 ///
 /// Newlines and carriage returns are trimmed from the end of the resulting `String`.
+/// At most [`crate::DEFAULT_MAX_LEN`] bytes are read; see [`from_tty_with_max_len`]
+/// to configure this.
 ///
 /// # Errors
 ///
 /// This function will return an I/O error if reading from the handle fails.
 pub fn from_tty() -> io::Result<Zeroizing<String>> {
+    from_tty_with_max_len(crate::DEFAULT_MAX_LEN)
+}
+
+/// Reads a password from the TTY, reading at most `max_len` bytes.
+///
+/// To shrink the window for an attacker (e.g. FFI code) closing and
+/// reopening the console handle mid-read to inject a canned response, the
+/// handle's identity (the underlying kernel object it refers to) is
+/// fingerprinted before the read and re-checked after it; a mismatch is
+/// reported as an I/O error. `max_len` bounds how much such an injected blob
+/// could be, shrinking the race window further.
+///
+/// Newlines and carriage returns are trimmed from the end of the resulting `String`.
+///
+/// # Errors
+///
+/// This function will return an I/O error if reading from the handle fails,
+/// if no newline is found within the first `max_len` bytes, or if the
+/// handle's identity changes between the start and the end of the read.
+pub fn from_tty_with_max_len(max_len: usize) -> io::Result<Zeroizing<String>> {
     let handle = unsafe {
         CreateFileA(
             b"CONIN$\x00".as_ptr() as PCSTR,
@@ -69,12 +199,156 @@ pub fn from_tty() -> io::Result<Zeroizing<String>> {
         return Err(io::Error::last_os_error());
     }
 
+    let fingerprint = HandleFingerprint::capture(handle)?;
     let mut reader = BufReader::new(unsafe { File::from_raw_handle(handle as _) });
 
     let _hidden_input = HiddenInput::new(handle)?;
-    let reader_return = crate::from_bufread(&mut reader);
+
+    let mut reader_return = crate::from_bufread_limited(&mut reader, max_len);
+    if reader_return.is_ok() && !fingerprint.matches(handle) {
+        reader_return = Err(io::Error::other(
+            "console handle identity changed while reading the password",
+        ));
+    }
 
     // Print a newline on Windows (otherwise whatever is printed next will be on the same line).
     io::stdout().write_all(b"\n")?;
     reader_return
 }
+
+/// Writes `prompt` to the console, then reads a password from it the same way [`from_tty`] does.
+///
+/// The prompt is written directly to `CONOUT$` rather than stdout, so it's
+/// displayed even when stdout is redirected.
+///
+/// # Errors
+///
+/// This function will return an I/O error if writing to or reading from the console fails.
+pub fn from_tty_with_prompt(prompt: &str) -> io::Result<Zeroizing<String>> {
+    let conout = unsafe {
+        CreateFileA(
+            b"CONOUT$\x00".as_ptr() as PCSTR,
+            GENERIC_READ | GENERIC_WRITE,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            std::ptr::null(),
+            OPEN_EXISTING,
+            0,
+            INVALID_HANDLE_VALUE,
+        )
+    };
+
+    if conout == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut writer = unsafe { File::from_raw_handle(conout as _) };
+    writer.write_all(prompt.as_bytes())?;
+    writer.flush()?;
+
+    let handle = unsafe {
+        CreateFileA(
+            b"CONIN$\x00".as_ptr() as PCSTR,
+            GENERIC_READ | GENERIC_WRITE,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            std::ptr::null(),
+            OPEN_EXISTING,
+            0,
+            INVALID_HANDLE_VALUE,
+        )
+    };
+
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error());
+    }
+
+    let fingerprint = HandleFingerprint::capture(handle)?;
+    let mut reader = BufReader::new(unsafe { File::from_raw_handle(handle as _) });
+
+    let _hidden_input = HiddenInput::new(handle)?;
+    let mut reader_return = crate::from_bufread_limited(&mut reader, crate::DEFAULT_MAX_LEN);
+    if reader_return.is_ok() && !fingerprint.matches(handle) {
+        reader_return = Err(io::Error::other(
+            "console handle identity changed while reading the password",
+        ));
+    }
+
+    // Print a newline on Windows (otherwise whatever is printed next will be on the same line).
+    io::stdout().write_all(b"\n")?;
+    reader_return
+}
+
+/// Reads a password from the TTY, printing `mask` in place of every character typed.
+///
+/// Unlike [`from_tty`], this disables line input and reads one byte at a
+/// time, so it can echo the mask character itself. Backspace removes the
+/// last character, and Ctrl-U clears the whole line.
+///
+/// # Errors
+///
+/// This function will return an I/O error if reading from or writing to the console fails.
+pub fn from_tty_masked(mask: char) -> io::Result<Zeroizing<String>> {
+    let handle = unsafe {
+        CreateFileA(
+            b"CONIN$\x00".as_ptr() as PCSTR,
+            GENERIC_READ | GENERIC_WRITE,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            std::ptr::null(),
+            OPEN_EXISTING,
+            0,
+            INVALID_HANDLE_VALUE,
+        )
+    };
+
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut tty = unsafe { File::from_raw_handle(handle as _) };
+    let mut out = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("CONOUT$")?;
+
+    let _raw_input = RawInput::new(handle)?;
+
+    let mut password = Zeroizing::new(String::new());
+    let mut byte = [0u8; 1];
+    loop {
+        if tty.read(&mut byte)? == 0 {
+            // EOF.
+            break;
+        }
+
+        match byte[0] {
+            b'\n' | b'\r' => {
+                out.write_all(b"\n")?;
+                break;
+            }
+            // Backspace.
+            0x08 => {
+                if password.pop().is_some() {
+                    out.write_all(b"\x08 \x08")?;
+                }
+            }
+            // Ctrl-U: clear the line.
+            0x15 => {
+                let erase = "\x08 \x08".repeat(password.chars().count());
+                password.clear();
+                out.write_all(erase.as_bytes())?;
+            }
+            first_byte => match crate::read_utf8_char(&mut tty, first_byte)? {
+                crate::Utf8Char::Char(c) => {
+                    password.push(c);
+                    write!(out, "{mask}")?;
+                }
+                crate::Utf8Char::Invalid => {}
+                crate::Utf8Char::Terminator => {
+                    out.write_all(b"\n")?;
+                    break;
+                }
+            },
+        }
+    }
+
+    Ok(password)
+}